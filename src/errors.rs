@@ -5,6 +5,7 @@ Error type, conversions, and macros
 use std;
 use notify_rust;
 use serde_json;
+use native_tls;
 
 pub type Result<T> = std::result::Result<T, Error>;
 
@@ -14,11 +15,14 @@ pub enum Error {
     Msg(String),
     Network(String),
     Command(i32),
+    Upgrade(String),
+    Signature(String),
     Io(std::io::Error),
     Nul(std::ffi::NulError),
     ParseInt(std::num::ParseIntError),
     Notify(notify_rust::Error),
     Json(serde_json::Error),
+    Tls(native_tls::Error),
 }
 
 
@@ -29,11 +33,14 @@ impl std::fmt::Display for Error {
             Msg(ref s)      => write!(f, "{}", s),
             Network(ref s)  => write!(f, "NetworkError: {}", s),
             Command(n)      => write!(f, "CommandError-StatusCode: {}", n),
+            Upgrade(ref s)  => write!(f, "UpgradeError: {}", s),
+            Signature(ref s) => write!(f, "SignatureError: {}", s),
             Io(ref e)       => write!(f, "IoError: {}", e),
             Nul(ref e)      => write!(f, "NulError: {}", e),
             ParseInt(ref e) => write!(f, "ParseIntError: {}", e),
             Notify(ref e)   => write!(f, "NotifyError: {}", e),
             Json(ref e)     => write!(f, "JsonError: {}", e),
+            Tls(ref e)      => write!(f, "TlsError: {}", e),
         }
     }
 }
@@ -52,6 +59,7 @@ impl std::error::Error for Error {
             ParseInt(ref e)     => e,
             Notify(ref e)       => e,
             Json(ref e)         => e,
+            Tls(ref e)          => e,
             _ => return None,
         })
     }
@@ -88,6 +96,12 @@ impl From<serde_json::Error> for Error {
     }
 }
 
+impl From<native_tls::Error> for Error {
+    fn from(e: native_tls::Error) -> Error {
+        Error::Tls(e)
+    }
+}
+
 
 macro_rules! format_err {
     ($e_type:expr, $literal:expr) => {
@@ -1,12 +1,26 @@
 use std::env;
+use std::fs;
 use std::io::{Read, Write};
 use std::net;
+use std::path;
+use std::process;
+use std::sync::{mpsc, Arc, Mutex};
+use std::thread;
+use std::time::Duration;
 
-use chrono::Local;
+use chrono::{DateTime, Local};
 use clap::ArgMatches;
 use env_logger;
+use native_tls::{Identity, TlsAcceptor};
 use serde_json;
 
+/// Default number of worker threads handling accepted connections
+static DEFAULT_WORKERS_STR: &'static str = "4";
+
+/// How long a worker will wait to read a complete note off a connection
+/// before giving up on it
+static READ_TIMEOUT: Duration = Duration::from_secs(10);
+
 use super::{ApiNote, Note, DEFAULT_PORT_STR};
 use crate::errors::*;
 
@@ -32,31 +46,278 @@ fn init_logger(log: bool) {
         .init();
 }
 
-/// Listen on the given address for incoming `ApiNote` messages
-/// and generate local notifications
+/// Location of the platform-appropriate service-unit file this command manages:
+/// a systemd user unit on Linux, a launchd plist on macOS
+///
+/// Errors:
+///     * Unsupported platform
+///     * Unable to determine `$HOME`
+fn service_unit_path() -> Result<path::PathBuf> {
+    let home = env::var("HOME")
+        .map_err(|_| format_err!(Error::Msg, "Unable to determine `$HOME` directory"))?;
+    if cfg!(target_os = "linux") {
+        Ok(path::Path::new(&home).join(".config/systemd/user/clin-listen.service"))
+    } else if cfg!(target_os = "macos") {
+        Ok(path::Path::new(&home).join("Library/LaunchAgents/io.clin.listen.plist"))
+    } else {
+        bail!(Error::Msg, "Installable services are only supported on Linux (systemd) and macOS (launchd)")
+    }
+}
+
+/// Render a systemd user-unit that runs `exe` with `args` as `clin-listen`
+fn systemd_unit(exe: &path::Path, args: &[String]) -> String {
+    format!(
+        "[Unit]\nDescription=clin notification listener\n\n[Service]\nExecStart={} {}\nRestart=on-failure\n\n[Install]\nWantedBy=default.target\n",
+        exe.display(),
+        args.join(" "),
+    )
+}
+
+/// Render a launchd plist that runs `exe` with `args` as `io.clin.listen`
+fn launchd_plist(exe: &path::Path, args: &[String]) -> String {
+    let mut program_args = vec![exe.display().to_string()];
+    program_args.extend(args.iter().cloned());
+    let items = program_args.iter()
+        .map(|a| format!("        <string>{}</string>", a))
+        .collect::<Vec<_>>()
+        .join("\n");
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n\
+         <!DOCTYPE plist PUBLIC \"-//Apple//DTD PLIST 1.0//EN\" \"http://www.apple.com/DTDs/PropertyList-1.0.dtd\">\n\
+         <plist version=\"1.0\">\n\
+         <dict>\n\
+         \x20   <key>Label</key>\n\
+         \x20   <string>io.clin.listen</string>\n\
+         \x20   <key>ProgramArguments</key>\n\
+         \x20   <array>\n{}\n    </array>\n\
+         \x20   <key>RunAtLoad</key>\n\
+         \x20   <true/>\n\
+         \x20   <key>KeepAlive</key>\n\
+         \x20   <true/>\n\
+         </dict>\n\
+         </plist>\n",
+        items,
+    )
+}
+
+/// Run a service-manager command, bailing with its stderr if it fails
+///
+/// Errors:
+///     * Unable to spawn `cmd`
+///     * `cmd` exited with a non-zero status
+fn run_service_command(cmd: &str, args: &[&str]) -> Result<()> {
+    let output = process::Command::new(cmd).args(args).output()
+        .map_err(|e| format_err!(Error::Msg, "Unable to run `{}`: {}", cmd, e))?;
+    if !output.status.success() {
+        bail!(Error::Msg, "`{} {}` failed: {}", cmd, args.join(" "), String::from_utf8_lossy(&output.stderr));
+    }
+    Ok(())
+}
+
+/// Generate and install a platform-appropriate background-service unit that
+/// re-invokes `clin listen` with the resolved host/port/log/tls/token/workers
+/// flags baked in, then enable/start it
+///
+/// Errors:
+///     * Unsupported platform
+///     * Writing the unit file
+///     * Running `systemctl`/`launchctl`
+fn install_service(matches: &ArgMatches) -> Result<()> {
+    let exe = env::current_exe()?;
+    let mut args = vec!["listen".to_owned()];
+    if let Some(port) = matches.value_of("port") {
+        args.push("--port".to_owned());
+        args.push(port.to_owned());
+    }
+    if matches.is_present("public") {
+        args.push("--public".to_owned());
+    }
+    if matches.is_present("log") {
+        args.push("--log".to_owned());
+    }
+    if matches.is_present("tls") {
+        args.push("--tls".to_owned());
+    }
+    if let Some(cert_path) = matches.value_of("tls_cert") {
+        args.push("--tls-cert".to_owned());
+        args.push(cert_path.to_owned());
+    }
+    if let Some(key_path) = matches.value_of("tls_key") {
+        args.push("--tls-key".to_owned());
+        args.push(key_path.to_owned());
+    }
+    if let Some(token) = matches.value_of("token") {
+        args.push("--token".to_owned());
+        args.push(token.to_owned());
+    }
+    if let Some(workers) = matches.value_of("workers") {
+        args.push("--workers".to_owned());
+        args.push(workers.to_owned());
+    }
+
+    let unit_path = service_unit_path()?;
+    if let Some(parent) = unit_path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+
+    if cfg!(target_os = "linux") {
+        fs::write(&unit_path, systemd_unit(&exe, &args))?;
+        run_service_command("systemctl", &["--user", "daemon-reload"])?;
+        run_service_command("systemctl", &["--user", "enable", "--now", "clin-listen"])?;
+    } else if cfg!(target_os = "macos") {
+        fs::write(&unit_path, launchd_plist(&exe, &args))?;
+        run_service_command("launchctl", &["load", &unit_path.to_string_lossy()])?;
+    }
+
+    println!("Installed and started clin-listen service at {:?}", unit_path);
+    Ok(())
+}
+
+/// Stop/disable and remove the installed `clin-listen` service unit
+///
+/// Errors:
+///     * Unsupported platform
+///     * Running `systemctl`/`launchctl`
+///     * Removing the unit file
+fn uninstall_service() -> Result<()> {
+    let unit_path = service_unit_path()?;
+
+    if cfg!(target_os = "linux") {
+        run_service_command("systemctl", &["--user", "disable", "--now", "clin-listen"])?;
+    } else if cfg!(target_os = "macos") {
+        run_service_command("launchctl", &["unload", &unit_path.to_string_lossy()])?;
+    }
+
+    if unit_path.exists() {
+        fs::remove_file(&unit_path)?;
+    }
+
+    println!("Uninstalled clin-listen service");
+    Ok(())
+}
+
+/// Build a `TlsAcceptor` from a PEM-encoded certificate/key pair
+///
+/// Errors:
+///     * Reading the cert/key files
+///     * Constructing the identity/acceptor
+fn build_tls_acceptor(cert_path: &str, key_path: &str) -> Result<TlsAcceptor> {
+    let cert_pem = fs::read(cert_path)?;
+    let key_pem = fs::read(key_path)?;
+    let identity = Identity::from_pkcs8(&cert_pem, &key_pem)?;
+    Ok(TlsAcceptor::new(identity)?)
+}
+
+/// Read and dispatch a single accepted connection: complete the (optional)
+/// TLS handshake, deserialize/verify the `ApiNote`, and push a local
+/// notification. Failures are logged and dropped rather than propagated,
+/// so one bad/slow connection can't take down the listener or its peers.
+fn handle_connection(
+    worker_id: usize,
+    stream: net::TcpStream,
+    acceptor: Option<Arc<TlsAcceptor>>,
+    token: Option<String>,
+    enqueued_at: DateTime<Local>,
+) {
+    if let Err(e) = stream.set_read_timeout(Some(READ_TIMEOUT)) {
+        warn!("worker {}: unable to set read timeout: {}", worker_id, e);
+        return;
+    }
+
+    let mut s = String::new();
+    let read_result = match acceptor {
+        Some(ref acceptor) => match acceptor.accept(stream) {
+            Ok(mut stream) => stream.read_to_string(&mut s),
+            Err(e) => {
+                warn!("worker {}: TLS handshake failed: {}", worker_id, e);
+                return;
+            }
+        },
+        None => {
+            let mut stream = stream;
+            stream.read_to_string(&mut s)
+        }
+    };
+    if let Err(e) = read_result {
+        warn!(
+            "worker {}: dropping connection enqueued at {} after read error/timeout: {}",
+            worker_id, enqueued_at.format("%Y-%m-%d_%H:%M:%S"), e
+        );
+        return;
+    }
+
+    if s == "ping" {
+        return;
+    }
+
+    let note: ApiNote = match serde_json::from_str(&s) {
+        Ok(note) => note,
+        Err(e) => {
+            warn!("worker {}: dropping connection with an invalid note payload: {}", worker_id, e);
+            return;
+        }
+    };
+    if let Some(ref token) = token {
+        if !note.verify(token) {
+            warn!("rejected notification with missing/invalid auth tag: [{}]: {}", note.title, note.msg);
+            return;
+        }
+    }
+    info!("[{}]: {}", note.title, note.msg);
+    if let Err(e) = Note::with_msg(&note.msg)
+        .title(&note.title)
+        .timeout(note.timeout)
+        .push()
+    {
+        warn!("worker {}: failed to push notification: {}", worker_id, e);
+    }
+}
+
+/// Listen on the given address for incoming `ApiNote` messages and generate
+/// local notifications. Accepted connections are handed off to a bounded
+/// pool of `workers` threads so a slow notification backend or a client that
+/// connects but never sends anything can't serialize/wedge the rest of the
+/// listener. Each accepted stream is wrapped in TLS first when `acceptor`
+/// is set.
 ///
 /// Errors:
 ///     * Binding to a <host:port>
-///     * Reading from opened stream
-///     * Deserializing incoming `ApiNote`s
-///     * Communication to the system notification-server
-fn listen(addr: &str) -> Result<()> {
-    info!("** Listening on {} **", addr);
+fn listen(addr: &str, acceptor: Option<TlsAcceptor>, token: Option<String>, workers: usize) -> Result<()> {
+    let workers = workers.max(1);
+    info!("** Listening on {} ({} workers) **", addr, workers);
 
     let listener = net::TcpListener::bind(&addr)?;
+    let acceptor = acceptor.map(Arc::new);
+    let (tx, rx) = mpsc::sync_channel::<(net::TcpStream, DateTime<Local>)>(workers * 4);
+    let rx = Arc::new(Mutex::new(rx));
+
+    for worker_id in 0..workers {
+        let rx = Arc::clone(&rx);
+        let acceptor = acceptor.clone();
+        let token = token.clone();
+        thread::spawn(move || loop {
+            let job = rx.lock().expect("listener worker queue lock poisoned").recv();
+            match job {
+                Ok((stream, enqueued_at)) => {
+                    handle_connection(worker_id, stream, acceptor.clone(), token.clone(), enqueued_at)
+                }
+                Err(_) => break,
+            }
+        });
+    }
+
     for stream in listener.incoming() {
-        let mut stream = stream?;
-        let mut s = String::new();
-        stream.read_to_string(&mut s)?;
-        if s == "ping" {
-            continue;
+        let stream = match stream {
+            Ok(stream) => stream,
+            Err(e) => {
+                warn!("failed to accept connection: {}", e);
+                continue;
+            }
+        };
+        let enqueued_at = Local::now();
+        if tx.send((stream, enqueued_at)).is_err() {
+            warn!("listener worker pool is gone, dropping connection");
         }
-        let note: ApiNote = serde_json::from_str(&s)?;
-        info!("[{}]: {}", note.title, note.msg);
-        Note::with_msg(&note.msg)
-            .title(&note.title)
-            .timeout(note.timeout)
-            .push()?;
     }
     Ok(())
 }
@@ -67,6 +328,13 @@ fn listen(addr: &str) -> Result<()> {
 ///     * Parsing argument integers
 ///     * Initializing the listener
 pub fn start_listener(matches: &ArgMatches) -> Result<()> {
+    if matches.is_present("uninstall_service") {
+        return uninstall_service();
+    }
+    if matches.is_present("install_service") {
+        return install_service(matches);
+    }
+
     init_logger(matches.is_present("log"));
     let host = if matches.is_present("public") {
         "0.0.0.0"
@@ -80,5 +348,30 @@ pub fn start_listener(matches: &ArgMatches) -> Result<()> {
         .unwrap_or(&fallback_port)
         .parse::<u32>()?;
     let addr = format!("{}:{}", host, port);
-    return listen(&addr);
+
+    let tls = matches.is_present("tls")
+        || env::var("CLIN_TLS")
+            .ok()
+            .and_then(|s| if s == "1" { Some(()) } else { None })
+            .is_some();
+    let acceptor = if tls {
+        let cert_path = matches.value_of("tls_cert")
+            .ok_or_else(|| format_err!(Error::Msg, "`--tls` requires `--tls-cert`"))?;
+        let key_path = matches.value_of("tls_key")
+            .ok_or_else(|| format_err!(Error::Msg, "`--tls` requires `--tls-key`"))?;
+        Some(build_tls_acceptor(cert_path, key_path)?)
+    } else {
+        None
+    };
+
+    let token = matches.value_of("token")
+        .map(|t| t.to_owned())
+        .or_else(|| env::var("CLIN_TOKEN").ok());
+
+    let workers = matches
+        .value_of("workers")
+        .unwrap_or(DEFAULT_WORKERS_STR)
+        .parse::<usize>()?;
+
+    return listen(&addr, acceptor, token, workers);
 }
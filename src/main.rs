@@ -4,9 +4,13 @@ extern crate serde_derive;
 #[macro_use]
 mod errors;
 mod listen;
+mod upgrade;
 
 use clap::{crate_version, App, AppSettings, Arg, ArgMatches, SubCommand};
+use hmac::{Hmac, Mac};
+use native_tls;
 use notify_rust::{Notification, Timeout};
+use sha2::Sha256;
 
 use std::env;
 use std::ffi;
@@ -27,13 +31,70 @@ pub static DEFAULT_TIMEOUT_STR: &'static str = "10000";
 pub static DEFAULT_TIMEOUT: u32 = 10000;
 pub static DEFAULT_TIMEOUT_SECONDS_STR: &'static str = "10";
 
+type HmacSha256 = Hmac<Sha256>;
+
+/// Hex-encode a byte slice
+fn hex_encode(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+/// Decode a hex string into bytes, returning `None` on malformed input
+fn hex_decode(hex: &str) -> Option<Vec<u8>> {
+    if hex.len() % 2 != 0 || !hex.bytes().all(|b| b.is_ascii_hexdigit()) { return None; }
+    (0..hex.len()).step_by(2)
+        .map(|i| u8::from_str_radix(&hex[i..i + 2], 16).ok())
+        .collect()
+}
+
+/// Output format for run reports and top-level errors
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum OutputFormat {
+    /// Human-readable prose on stderr, the default
+    Text,
+    /// A single structured JSON object on stdout, for scripting
+    Json,
+}
+impl OutputFormat {
+    /// Resolve the requested format from `--format`, falling back to `CLIN_FORMAT`
+    /// and then `OutputFormat::Text`
+    fn from_matches(matches: &ArgMatches) -> OutputFormat {
+        let format = matches.value_of("format")
+            .map(|f| f.to_owned())
+            .or_else(|| env::var("CLIN_FORMAT").ok());
+        match format.as_ref().map(|f| f.as_str()) {
+            Some("json") => OutputFormat::Json,
+            _ => OutputFormat::Text,
+        }
+    }
+}
+
+/// Structured description of a completed `run_command` invocation, emitted as
+/// a single JSON object on stdout when `OutputFormat::Json` is active
+#[derive(Debug, Serialize)]
+struct RunReport {
+    command: String,
+    exit_status: i32,
+    success: bool,
+    title: String,
+    message: String,
+    sent_over_wire: bool,
+    host: Option<String>,
+    port: Option<u32>,
+    /// Set if pushing the desktop/remote notification failed, e.g. no
+    /// notification daemon is running -- the command's own result above is
+    /// unaffected, this only reports the notification delivery itself
+    push_error: Option<String>,
+}
+
 /// Notification information to send over the wire from a remote client
 /// to a local listening server
-#[derive(Debug, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ApiNote {
     title: String,
     msg: String,
     timeout: u32,
+    /// HMAC-SHA256 of this note (with `tag` itself set to `None`), keyed by a shared token
+    tag: Option<String>,
 }
 impl ApiNote {
     /// Create a new api-note with a message and default values
@@ -42,6 +103,7 @@ impl ApiNote {
             title: DEFAULT_TITLE.to_owned(),
             msg: msg.to_owned(),
             timeout: DEFAULT_TIMEOUT,
+            tag: None,
         }
     }
 
@@ -56,6 +118,48 @@ impl ApiNote {
         self.timeout = millis;
         self
     }
+
+    /// Build the keyed HMAC-SHA256 over this note's serialized body (with `tag` cleared)
+    ///
+    /// Errors:
+    ///     * Serializing the note
+    ///     * Constructing an HMAC from `token`
+    fn mac(&self, token: &str) -> Result<HmacSha256> {
+        let mut body = self.clone();
+        body.tag = None;
+        let payload = serde_json::to_string(&body)?;
+        let mut mac = HmacSha256::new_varkey(token.as_bytes())
+            .map_err(|_| format_err!(Error::Msg, "Invalid HMAC token"))?;
+        mac.input(payload.as_bytes());
+        Ok(mac)
+    }
+
+    /// Compute the hex-encoded HMAC-SHA256 of this note's serialized body, keyed with `token`
+    fn hmac(&self, token: &str) -> Result<String> {
+        Ok(hex_encode(&self.mac(token)?.result().code()))
+    }
+
+    /// Compute and attach this note's `tag`, keyed with `token`
+    fn sign(mut self, token: &str) -> Result<ApiNote> {
+        self.tag = Some(self.hmac(token)?);
+        Ok(self)
+    }
+
+    /// Check the note's `tag` against a freshly computed HMAC-SHA256, keyed with `token`,
+    /// using `Mac::verify`'s constant-time comparison so response timing can't leak
+    /// information about a forged tag
+    fn verify(&self, token: &str) -> bool {
+        match self.tag {
+            Some(ref tag) => {
+                let expected = match hex_decode(tag) {
+                    Some(bytes) => bytes,
+                    None => return false,
+                };
+                self.mac(token).map(|mac| mac.verify(&expected).is_ok()).unwrap_or(false)
+            }
+            None => false,
+        }
+    }
 }
 
 /// Notification builder
@@ -66,6 +170,8 @@ pub struct Note {
     pub send: bool,
     pub host: String,
     pub port: u32,
+    pub tls: bool,
+    pub token: Option<String>,
 }
 impl Note {
     /// Create a new notification with a given message and default values
@@ -77,6 +183,8 @@ impl Note {
             send: false,
             host: DEFAULT_HOST.to_owned(),
             port: DEFAULT_PORT,
+            tls: false,
+            token: None,
         }
     }
 
@@ -115,6 +223,18 @@ impl Note {
         self
     }
 
+    /// Set whether the connection to a listener should be wrapped in TLS
+    pub fn tls(mut self, tls: bool) -> Note {
+        self.tls = tls;
+        self
+    }
+
+    /// Set the shared token used to sign outgoing notifications, overriding the default
+    pub fn token(mut self, token: Option<String>) -> Note {
+        self.token = token;
+        self
+    }
+
     fn from_matches(matches: &ArgMatches) -> Result<Note> {
         // Capture default and overridden notification arguments
         let send = matches.is_present("send")
@@ -122,6 +242,14 @@ impl Note {
                 .ok()
                 .and_then(|s| if s == "1" { Some(()) } else { None })
                 .is_some();
+        let tls = matches.is_present("tls")
+            || env::var("CLIN_TLS")
+                .ok()
+                .and_then(|s| if s == "1" { Some(()) } else { None })
+                .is_some();
+        let token = matches.value_of("token")
+            .map(|t| t.to_owned())
+            .or_else(|| env::var("CLIN_TOKEN").ok());
         let fallback_host = env::var("CLIN_SEND_HOST").unwrap_or_else(|_| DEFAULT_HOST.to_string());
         let host = matches.value_of("host").unwrap_or(&fallback_host);
         let fallback_port =
@@ -140,7 +268,9 @@ impl Note {
             .timeout(timeout)
             .send(send)
             .host(host)
-            .port(port);
+            .port(port)
+            .tls(tls)
+            .token(token);
         Ok(note)
     }
 
@@ -148,19 +278,21 @@ impl Note {
     ///
     /// Errors:
     ///     * Serializing `ApiNote`
+    ///     * Signing the note with the configured token
     ///     * Connecting to a listener
     ///     * Writing to listener stream
     ///     * Communicating to the system notification-server
     pub fn push(self) -> Result<()> {
         if self.send {
-            use io::Write;
-            let addr = format!("{}:{}", self.host, self.port);
             let note = ApiNote::with_msg(&self.msg)
                 .title(&self.title)
                 .timeout(self.timeout);
+            let note = match self.token {
+                Some(ref token) => note.sign(token)?,
+                None => note,
+            };
             let note = serde_json::to_string(&note)?;
-            let mut stream = net::TcpStream::connect(&addr)?;
-            stream.write(note.as_bytes())?;
+            send_over_wire(&self.host, self.port, self.tls, note.as_bytes())?;
         } else {
             Notification::new()
                 .icon(DEFAULT_ICON)
@@ -173,19 +305,43 @@ impl Note {
     }
 }
 
-/// Check if we can connect to the specified receiver
+/// Connect to `host:port`, optionally wrapping the stream in TLS, and write `bytes`.
+/// Self-signed certs are accepted when connecting to a local `host`, to support
+/// ad-hoc TLS setups without a trusted CA.
 ///
 /// Errors:
 ///     * Connecting to listener
-///     * Writing to listener stream
-fn can_connect(host: &str, port: u32) -> Result<()> {
+///     * Establishing a TLS session
+///     * Writing to the (possibly wrapped) stream
+fn send_over_wire(host: &str, port: u32, tls: bool, bytes: &[u8]) -> Result<()> {
     use io::Write;
     let addr = format!("{}:{}", host, port);
-    let mut stream = net::TcpStream::connect(&addr)?;
-    stream.write("ping".as_bytes())?;
+    let stream = net::TcpStream::connect(&addr)?;
+    if tls {
+        let mut builder = native_tls::TlsConnector::builder();
+        if host == "127.0.0.1" || host == "localhost" {
+            builder.danger_accept_invalid_certs(true);
+        }
+        let connector = builder.build()?;
+        let mut stream = connector.connect(host, stream)
+            .map_err(|e| format_err!(Error::Network, "TLS handshake with `{}` failed: {}", host, e))?;
+        stream.write_all(bytes)?;
+    } else {
+        let mut stream = stream;
+        stream.write_all(bytes)?;
+    }
     Ok(())
 }
 
+/// Check if we can connect to the specified receiver
+///
+/// Errors:
+///     * Connecting to listener
+///     * Writing to listener stream
+fn can_connect(host: &str, port: u32, tls: bool) -> Result<()> {
+    send_over_wire(host, port, tls, "ping".as_bytes())
+}
+
 /// Run a command in foreground
 ///
 /// Errors:
@@ -215,7 +371,7 @@ fn collect_cmd_note(matches: &ArgMatches) -> Result<(String, Note)> {
     let note = Note::from_matches(&matches)?;
 
     // If sending, make sure specified connection works
-    if note.send && can_connect(&note.host, note.port).is_err() {
+    if note.send && can_connect(&note.host, note.port, note.tls).is_err() {
         bail!(
             Error::Network,
             "Unable to connect to clin-listener at `{}:{}`",
@@ -249,36 +405,37 @@ fn collect_cmd_note(matches: &ArgMatches) -> Result<(String, Note)> {
 
 #[cfg(feature = "update")]
 fn update(matches: &ArgMatches) -> Result<()> {
-    let mut builder = self_update::backends::github::Update::configure();
+    let channel = match matches.value_of("channel") {
+        None | Some("stable") => upgrade::Channel::Stable,
+        Some("beta") => upgrade::Channel::Beta,
+        Some("nightly") => upgrade::Channel::Nightly,
+        Some(other) => bail!(Error::Msg, "Unknown `--channel` value: `{}` (expected `stable`, `beta`, or `nightly`)", other),
+    };
+    let filter = match matches.value_of("filter") {
+        None | Some("all") => upgrade::UpdateFilter::All,
+        Some("critical") => upgrade::UpdateFilter::Critical,
+        Some(other) => bail!(Error::Msg, "Unknown `--filter` value: `{}` (expected `all` or `critical`)", other),
+    };
+
+    let mut builder = upgrade::Updater::configure();
 
     builder
         .repo_owner("jaemk")
         .repo_name("clin")
-        .target(self_update::get_target())
-        .bin_name("clin")
-        .show_download_progress(true)
+        .bin_name(upgrade::BIN_NAME)
+        .current_version(upgrade::CURRENT_VERSION)
+        .channel(channel)
+        .filter(filter)
         .no_confirm(matches.is_present("no_confirm"))
-        .current_version(APP_VERSION);
-
-    if matches.is_present("quiet") {
-        builder.show_output(false).show_download_progress(false);
-    }
+        .skip_verify(matches.is_present("skip_verify"))
+        .show_progress(!matches.is_present("quiet"));
 
-    let status = builder.build()?.update()?;
-    match status {
-        self_update::Status::UpToDate(v) => {
-            println!("Already up to date [v{}]!", v);
-        }
-        self_update::Status::Updated(v) => {
-            println!("Updated to {}!", v);
-        }
-    }
-    return Ok(());
+    builder.build()?.update()
 }
 
 #[cfg(not(feature = "update"))]
 fn update(_: &ArgMatches) -> Result<()> {
-    bail!(Error::Msg, "This executable was not compiled with `self_update` features enabled via `--features update`");
+    bail!(Error::Msg, "This executable was not compiled with self-update features enabled via `--features update`");
 }
 
 /// Dispatch over arguments
@@ -302,14 +459,37 @@ fn run(matches: ArgMatches) -> Result<()> {
         return Ok(());
     }
 
+    let format = OutputFormat::from_matches(&matches);
     let (cmd, note) = collect_cmd_note(&matches)?;
-    eprintln!("clin: `{}`", cmd);
+    if format == OutputFormat::Text {
+        eprintln!("clin: `{}`", cmd);
+    }
 
-    let title = match run_command(&cmd) {
-        Err(Error::Command(ret)) => format!("Error ✗ -- exit status: {}", ret),
+    let (exit_status, title) = match run_command(&cmd) {
+        Err(Error::Command(ret)) => (ret, format!("Error ✗ -- exit status: {}", ret)),
         Err(e) => return Err(e),
-        Ok(_) => "Complete ✓".to_string(),
+        Ok(_) => (0, "Complete ✓".to_string()),
     };
+
+    if format == OutputFormat::Json {
+        let mut report = RunReport {
+            command: cmd,
+            exit_status,
+            success: exit_status == 0,
+            title: title.clone(),
+            message: note.msg.clone(),
+            sent_over_wire: note.send,
+            host: if note.send { Some(note.host.clone()) } else { None },
+            port: if note.send { Some(note.port) } else { None },
+            push_error: None,
+        };
+        if let Err(e) = note.title(&title).push() {
+            report.push_error = Some(e.to_string());
+        }
+        println!("{}", serde_json::to_string(&report)?);
+        return Ok(());
+    }
+
     note.title(&title).push()
 }
 
@@ -341,7 +521,22 @@ clin -m \"just post this message\"")
                              .long("quiet")
                              .short("q")
                              .required(false)
-                             .takes_value(false))))
+                             .takes_value(false))
+                        .arg(Arg::with_name("skip_verify")
+                             .help("Skip checksum verification (signatures are always verified when published)")
+                             .long("skip-verify")
+                             .required(false)
+                             .takes_value(false))
+                        .arg(Arg::with_name("channel")
+                             .help("Release channel to track: `stable` (default), `beta`, or `nightly`")
+                             .long("channel")
+                             .required(false)
+                             .takes_value(true))
+                        .arg(Arg::with_name("filter")
+                             .help("Restrict eligible releases: `all` (default) or `critical`")
+                             .long("filter")
+                             .required(false)
+                             .takes_value(true))))
         .subcommand(SubCommand::with_name("listen")
                     .about("Listen for network notifications")
             .arg(Arg::with_name("log")
@@ -359,13 +554,59 @@ clin -m \"just post this message\"")
                  .help("Listen publicly on 0.0.0.0, instead of 127.0.0.1")
                  .long("public")
                  .required(false)
-                 .takes_value(false)))
+                 .takes_value(false))
+            .arg(Arg::with_name("tls")
+                 .help("Accept connections over TLS, also enabled by `CLIN_TLS=1`")
+                 .long("tls")
+                 .required(false)
+                 .takes_value(false))
+            .arg(Arg::with_name("tls_cert")
+                 .help("Path to a PEM-encoded TLS certificate, required with `--tls`")
+                 .long("tls-cert")
+                 .required(false)
+                 .takes_value(true))
+            .arg(Arg::with_name("tls_key")
+                 .help("Path to the PEM-encoded private key for `--tls-cert`, required with `--tls`")
+                 .long("tls-key")
+                 .required(false)
+                 .takes_value(true))
+            .arg(Arg::with_name("token")
+                 .help("Shared secret used to authenticate incoming notifications, overrides `CLIN_TOKEN`")
+                 .long("token")
+                 .required(false)
+                 .takes_value(true))
+            .arg(Arg::with_name("workers")
+                 .help("Number of worker threads handling accepted connections, defaults to `4`")
+                 .long("workers")
+                 .required(false)
+                 .takes_value(true))
+            .arg(Arg::with_name("install_service")
+                 .help("Install and start a background service (systemd user unit on Linux, launchd agent on macOS) running `clin listen` with the given flags")
+                 .long("install-service")
+                 .required(false)
+                 .takes_value(false))
+            .arg(Arg::with_name("uninstall_service")
+                 .help("Stop and remove a previously installed `clin listen` background service")
+                 .long("uninstall-service")
+                 .required(false)
+                 .takes_value(false)
+                 .conflicts_with("install_service")))
         .arg(Arg::with_name("send")
              .help("Send notification to a clin-listener, also enabled by `CLIN_SEND=1`")
              .long("send")
              .short("s")
              .required(false)
              .takes_value(false))
+        .arg(Arg::with_name("tls")
+             .help("Send notification over TLS, also enabled by `CLIN_TLS=1`")
+             .long("tls")
+             .required(false)
+             .takes_value(false))
+        .arg(Arg::with_name("token")
+             .help("Shared secret used to sign outgoing notifications, overrides `CLIN_TOKEN`")
+             .long("token")
+             .required(false)
+             .takes_value(true))
         .arg(Arg::with_name("host")
              .help(&format!("Host to send notification to, defaults to `{}`, overrides `CLIN_SEND_HOST`", DEFAULT_HOST))
              .long("host")
@@ -395,6 +636,11 @@ clin -m \"just post this message\"")
              .short("c")
              .required(false)
              .takes_value(true))
+        .arg(Arg::with_name("format")
+             .help("Output format for run reports/errors: `text` (default) or `json`, overrides `CLIN_FORMAT`")
+             .long("format")
+             .required(false)
+             .takes_value(true))
         .arg(Arg::with_name("cmd")
              .help("Specify a command as arguments trailing an initial `--`")
              .multiple(true)
@@ -402,8 +648,71 @@ clin -m \"just post this message\"")
              .last(true))
         .get_matches();
 
+    let format = OutputFormat::from_matches(&matches);
     if let Err(e) = run(matches) {
-        eprintln!("[ERROR] {}", e);
+        match format {
+            OutputFormat::Json => {
+                let err_obj = serde_json::json!({"error": e.to_string()});
+                println!("{}", err_obj);
+            }
+            OutputFormat::Text => eprintln!("[ERROR] {}", e),
+        }
         process::exit(1);
     }
 }
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn format_matches(args: &[&str]) -> ArgMatches<'static> {
+        App::new("test")
+            .arg(Arg::with_name("format").long("format").takes_value(true))
+            .get_matches_from(args)
+    }
+
+    #[test]
+    fn output_format_defaults_to_text() {
+        let matches = format_matches(&["test"]);
+        assert_eq!(OutputFormat::from_matches(&matches), OutputFormat::Text);
+    }
+
+    #[test]
+    fn output_format_parses_json_flag() {
+        let matches = format_matches(&["test", "--format", "json"]);
+        assert_eq!(OutputFormat::from_matches(&matches), OutputFormat::Json);
+    }
+
+    #[test]
+    fn apinote_sign_then_verify_round_trips() {
+        let note = ApiNote::with_msg("hello").title("t").sign("secret").unwrap();
+        assert!(note.verify("secret"));
+    }
+
+    #[test]
+    fn apinote_verify_rejects_wrong_token() {
+        let note = ApiNote::with_msg("hello").title("t").sign("secret").unwrap();
+        assert!(!note.verify("wrong-secret"));
+    }
+
+    #[test]
+    fn apinote_verify_rejects_tampered_message() {
+        let mut note = ApiNote::with_msg("hello").title("t").sign("secret").unwrap();
+        note.msg = "tampered".to_owned();
+        assert!(!note.verify("secret"));
+    }
+
+    #[test]
+    fn apinote_verify_rejects_missing_tag() {
+        let note = ApiNote::with_msg("hello").title("t");
+        assert!(!note.verify("secret"));
+    }
+
+    #[test]
+    fn apinote_verify_rejects_malformed_tag() {
+        let mut note = ApiNote::with_msg("hello").title("t").sign("secret").unwrap();
+        note.tag = Some("not-hex!!".to_owned());
+        assert!(!note.verify("secret"));
+    }
+}
@@ -6,31 +6,136 @@ use std::path;
 
 use reqwest;
 use serde_json;
+use sha2::{Digest, Sha256};
+use minisign_verify::{PublicKey, Signature};
 use tempdir;
 use flate2;
 use tar;
+use zip;
+use semver;
+use dirs;
 use errors::*;
 
-pub static CURRENT_VERSION: &'static str = ""; //crate_version!();
-pub static API_URL: &'static str = "https://api.github.com/repos/jaemk/clin/releases/latest";
+pub static BIN_NAME: &'static str = "clin";
+pub static CURRENT_VERSION: &'static str = clap::crate_version!();
+
+/// Public half of the minisign keypair used to sign `clin` releases.
+/// The matching secret key never leaves the release machine.
+pub static TRUSTED_PUBLIC_KEY: &'static str = "\
+RWQf6LRCGA9i53mlYecO4IzT51TGPpvWucNSCh1CBM0QTaLn73Y7GFO3";
+
+
+/// Release channel to track when resolving the latest release
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum Channel {
+    /// Newest non-prerelease tag
+    Stable,
+    /// Newest tag whose name contains `beta`
+    Beta,
+    /// Newest tag whose name contains `nightly`
+    Nightly,
+}
+
+
+/// Which releases on the configured `Channel` are eligible for an update
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum UpdateFilter {
+    /// Any release on the configured channel
+    All,
+    /// Only releases marked critical, see `is_critical_release`
+    Critical,
+    /// No filtering; alias for `All`
+    None,
+}
+
+
+/// Check a release's body/notes for a `critical: true` marker line or a
+/// `critical` label, used to gate `UpdateFilter::Critical`
+fn is_critical_release(release: &serde_json::Value) -> bool {
+    let body = release["body"].as_str().unwrap_or("");
+    if body.lines().any(|line| line.trim().eq_ignore_ascii_case("critical: true")) {
+        return true;
+    }
+    release["labels"].as_array()
+        .map(|labels| labels.iter().any(|l| l.as_str() == Some("critical")))
+        .unwrap_or(false)
+}
 
 
 fn get_target() -> Result<String> {
-    let arch_config = (cfg!(target_arch = "x86"), cfg!(target_arch = "x86_64"));
+    let arch_config = (
+        cfg!(target_arch = "x86"),
+        cfg!(target_arch = "x86_64"),
+        cfg!(target_arch = "arm"),
+        cfg!(target_arch = "aarch64"),
+    );
     let arch = match arch_config {
-        (true, _) => "i686",
-        (_, true) => "x86_64",
+        (true, _, _, _) => "i686",
+        (_, true, _, _) => "x86_64",
+        (_, _, true, _) => "armv7",
+        (_, _, _, true) => "aarch64",
         _ => bail!(Error::Upgrade, "Unable to determine target-architecture"),
     };
 
+    if cfg!(target_os = "windows") {
+        let env = if cfg!(target_env = "gnu") { "gnu" } else { "msvc" };
+        return Ok(format!("{}-pc-windows-{}", arch, env));
+    }
+
     let os_config = (cfg!(target_os = "macos"), cfg!(target_os = "linux"));
-    let os = match os_config {
-        (true, _) => "apple-darwin",
-        (_, true) => "unknown-linux-gnu",
+    match os_config {
+        (true, _) => Ok(format!("{}-apple-darwin", arch)),
+        (_, true) => {
+            if cfg!(target_env = "musl") {
+                Ok(format!("{}-unknown-linux-musl", arch))
+            } else if arch == "armv7" {
+                Ok(format!("{}-unknown-linux-gnueabihf", arch))
+            } else {
+                Ok(format!("{}-unknown-linux-gnu", arch))
+            }
+        }
         _ => bail!(Error::Upgrade, "Unable to determine target-os"),
-    };
+    }
+}
+
+
+/// Parse a `v`-prefixed or bare semver tag into a comparable `semver::Version`
+fn parse_semver(tag: &str) -> Result<semver::Version> {
+    semver::Version::parse(tag.trim_left_matches("v"))
+        .map_err(|e| format_err!(Error::Upgrade, "Unable to parse version `{}`: {}", tag, e))
+}
+
 
-    Ok(format!("{}-{}", arch, os))
+/// On-disk record of the last release we successfully installed, keyed by
+/// `bin_name`, so repeat `update()` calls can skip redundant downloads
+#[derive(Debug, Default, Serialize, Deserialize)]
+struct UpdateCache {
+    installed_tag: Option<String>,
+    asset_etag: Option<String>,
+}
+impl UpdateCache {
+    fn path(bin_name: &str) -> Result<path::PathBuf> {
+        let mut dir = dirs::config_dir()
+            .ok_or_else(|| format_err!(Error::Upgrade, "Unable to determine config directory"))?;
+        dir.push(bin_name);
+        fs::create_dir_all(&dir)?;
+        dir.push("update_cache.json");
+        Ok(dir)
+    }
+
+    /// Load the cache, falling back to an empty one if it doesn't exist or can't be parsed
+    fn load(bin_name: &str) -> UpdateCache {
+        Self::path(bin_name).ok()
+            .and_then(|p| fs::read_to_string(p).ok())
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+
+    fn save(&self, bin_name: &str) -> Result<()> {
+        let path = Self::path(bin_name)?;
+        fs::write(path, serde_json::to_string(self)?)?;
+        Ok(())
+    }
 }
 
 
@@ -53,6 +158,29 @@ impl ReleaseAsset {
 }
 
 
+/// Resolve the expected checksum for `asset_name`, either from a dedicated
+/// `<asset_name>.sha256` file or by looking it up in a combined `SHA256SUMS` listing.
+fn find_expected_digest(assets: &[ReleaseAsset], asset_name: &str) -> Result<Option<String>> {
+    let digest_name = format!("{}.sha256", asset_name);
+    if let Some(ra) = assets.iter().find(|ra| ra.name == digest_name) {
+        let text = reqwest::get(&ra.download_url)?.text()?;
+        return Ok(Some(text.trim().to_lowercase()));
+    }
+    if let Some(ra) = assets.iter().find(|ra| ra.name == "SHA256SUMS") {
+        let text = reqwest::get(&ra.download_url)?.text()?;
+        for line in text.lines() {
+            let mut parts = line.split_whitespace();
+            if let (Some(digest), Some(name)) = (parts.next(), parts.next()) {
+                if name.trim_left_matches('*') == asset_name {
+                    return Ok(Some(digest.to_lowercase()));
+                }
+            }
+        }
+    }
+    Ok(None)
+}
+
+
 fn prompt(msg: &str) -> Result<()> {
     use std::io::Write;
 
@@ -92,10 +220,13 @@ fn display_dl_progress(total_size: u64, bytes_read: u64, clear_size: usize) -> R
 }
 
 
-fn download_to_file_with_progress<T: io::Read, U: io::Write>(mut src: T, mut dest: U, size: u64, show_progress: bool) -> Result<()> {
+/// Stream `src` into `dest`, returning the hex-encoded SHA-256 digest of
+/// everything that was written
+fn download_to_file_with_progress<T: io::Read, U: io::Write>(mut src: T, mut dest: U, size: u64, show_progress: bool) -> Result<String> {
     let mut buf = vec![0; 4096];
     let mut bytes_read = 0;
     let mut clear_size = 0;
+    let mut hasher = Sha256::new();
     loop {
         buf.resize(4096, 0);  // make sure buf is full size before reading
         if show_progress {
@@ -105,19 +236,58 @@ fn download_to_file_with_progress<T: io::Read, U: io::Write>(mut src: T, mut des
         if n == 0 { break; }
         bytes_read += n;
         buf.truncate(n);     // read doesn't always fill the entire buf, truncate before writing
+        hasher.input(&buf);
         dest.write_all(&mut buf)?;
     }
     if show_progress { println!(" ✓"); }
-    Ok(())
+    Ok(format!("{:x}", hasher.result()))
 }
 
 
-fn extract_tarball(tarball: &path::Path, dir: &path::Path) -> Result<path::PathBuf> {
-    let tarball = fs::File::open(tarball)?;
-    let tar = flate2::read::GzDecoder::new(tarball)?;
-    let mut archive = tar::Archive::new(tar);
-    archive.unpack(dir)?;
-    Ok(dir.join("clin"))
+/// Extract a `.tar.gz`/`.tgz` or `.zip` release archive into `dir`, returning
+/// the path to the extracted `bin_name` executable
+fn extract_tarball(archive_path: &path::Path, dir: &path::Path, bin_name: &str) -> Result<path::PathBuf> {
+    let name = archive_path.file_name()
+        .and_then(|n| n.to_str())
+        .ok_or_else(|| format_err!(Error::Upgrade, "Release asset has no file name"))?;
+
+    if name.ends_with(".zip") {
+        extract_zip(archive_path, dir, bin_name)
+    } else {
+        let tarball = fs::File::open(archive_path)?;
+        let tar = flate2::read::GzDecoder::new(tarball)?;
+        let mut archive = tar::Archive::new(tar);
+        archive.unpack(dir)?;
+        Ok(dir.join(bin_name))
+    }
+}
+
+
+/// Extract a `.zip` release archive into `dir`, returning the path to the
+/// entry matching `bin_name` (with a `.exe` suffix on Windows)
+fn extract_zip(zip_path: &path::Path, dir: &path::Path, bin_name: &str) -> Result<path::PathBuf> {
+    let bin_name = if cfg!(target_os = "windows") {
+        format!("{}.exe", bin_name)
+    } else {
+        bin_name.to_owned()
+    };
+
+    let zip_file = fs::File::open(zip_path)?;
+    let mut archive = zip::ZipArchive::new(zip_file)
+        .map_err(|e| format_err!(Error::Upgrade, "Unable to read zip archive: {}", e))?;
+
+    for i in 0..archive.len() {
+        let mut entry = archive.by_index(i)
+            .map_err(|e| format_err!(Error::Upgrade, "Unable to read zip entry: {}", e))?;
+        let entry_name = entry.name().to_owned();
+        if path::Path::new(&entry_name).file_name().and_then(|n| n.to_str()) == Some(&bin_name) {
+            let out_path = dir.join(&bin_name);
+            let mut out_file = fs::File::create(&out_path)?;
+            io::copy(&mut entry, &mut out_file)?;
+            return Ok(out_path);
+        }
+    }
+    bail!(Error::Upgrade, "No `{}` entry found in zip archive", bin_name)
 }
 
 
@@ -133,68 +303,386 @@ fn replace_exe(current_exe: &path::Path, new_exe: &path::Path, tmp_file: &path::
 }
 
 
-/// Upgrade the current binary to the latest release
-pub fn run(show_progress: bool) -> Result<()> {
-    let current_exe = env::current_exe()?;
-    let target = get_target()?;
+/// Builder for configuring an [`Updater`](struct.Updater.html)
+pub struct UpdaterBuilder {
+    repo_owner: Option<String>,
+    repo_name: Option<String>,
+    bin_name: Option<String>,
+    target: Option<String>,
+    current_version: Option<String>,
+    channel: Channel,
+    filter: UpdateFilter,
+    show_progress: bool,
+    no_confirm: bool,
+    skip_verify: bool,
+}
+impl UpdaterBuilder {
+    fn new() -> UpdaterBuilder {
+        UpdaterBuilder {
+            repo_owner: None,
+            repo_name: None,
+            bin_name: None,
+            target: None,
+            current_version: None,
+            channel: Channel::Stable,
+            filter: UpdateFilter::All,
+            show_progress: false,
+            no_confirm: false,
+            skip_verify: false,
+        }
+    }
 
-    let mut resp = reqwest::get(API_URL)?;
-    if !resp.status().is_success() { bail!(Error::Upgrade, "api request failed with status: {:?}", resp.status()) }
+    /// Set the GitHub repo owner to fetch releases from
+    pub fn repo_owner(&mut self, owner: &str) -> &mut Self {
+        self.repo_owner = Some(owner.to_owned());
+        self
+    }
 
-    let latest: serde_json::Value = resp.json()?;
+    /// Set the GitHub repo name to fetch releases from
+    pub fn repo_name(&mut self, name: &str) -> &mut Self {
+        self.repo_name = Some(name.to_owned());
+        self
+    }
 
-    let latest_tag = latest["tag_name"].as_str()
-        .ok_or_else(|| format_err!(Error::Upgrade, "No tag_name found for latest release"))?
-        .trim_left_matches("v");
-    if CURRENT_VERSION == latest_tag {
-        println!("Already up to date! -- v{}", CURRENT_VERSION);
-        return Ok(())
+    /// Set the name of the executable contained in release assets,
+    /// defaults to `BIN_NAME`
+    pub fn bin_name(&mut self, name: &str) -> &mut Self {
+        self.bin_name = Some(name.to_owned());
+        self
     }
 
-    println!("New release found! v{} --> v{}", CURRENT_VERSION, latest_tag);
+    /// Override the target triple used to filter release assets,
+    /// defaults to the result of `get_target()`
+    pub fn target(&mut self, target: &str) -> &mut Self {
+        self.target = Some(target.to_owned());
+        self
+    }
 
-    let latest_assets = latest["assets"].as_array().ok_or_else(|| format_err!(Error::Upgrade, "No release assets found!"))?;
+    /// Set the currently running version to compare against the latest release
+    pub fn current_version(&mut self, version: &str) -> &mut Self {
+        self.current_version = Some(version.to_owned());
+        self
+    }
 
-    let target_asset = latest_assets.iter().map(ReleaseAsset::from_asset).collect::<Result<Vec<ReleaseAsset>>>();
-    let target_asset = target_asset?.into_iter()
-        .filter(|ra| ra.name.contains(&target))
-        .nth(0)
-        .ok_or_else(|| format_err!(Error::Upgrade, "No release asset found for current target: `{}`", target))?;
+    /// Track the given release channel instead of the default `Channel::Stable`
+    pub fn channel(&mut self, channel: Channel) -> &mut Self {
+        self.channel = channel;
+        self
+    }
 
-    println!("\nclin release status:");
-    println!("  * Current executable: {:?}", current_exe);
-    println!("  * New executable tarball: {:?}", target_asset.name);
-    println!("  * New executable download url: {:?}", target_asset.download_url);
-    println!("\nThe following operations will be executed:");
-    println!("  - Download/extract new release");
-    println!("  - Overwrite current executable with new release");
-    prompt("Do you want to continue? [Y/n] ")?;
+    /// Restrict eligible releases instead of the default `UpdateFilter::All`
+    pub fn filter(&mut self, filter: UpdateFilter) -> &mut Self {
+        self.filter = filter;
+        self
+    }
 
-    let tmp_dir = tempdir::TempDir::new("clin-download")?;
-    let tmp_tarball_path = tmp_dir.path().join(&target_asset.name);
-    let mut tmp_tarball = fs::File::create(&tmp_tarball_path)?;
+    /// Set whether a download progress bar should be displayed
+    pub fn show_progress(&mut self, show: bool) -> &mut Self {
+        self.show_progress = show;
+        self
+    }
 
-    println!("Downloading...");
-    let mut resp = reqwest::get(&target_asset.download_url)?;
-    let content_length = resp.headers()
-        .get::<reqwest::header::ContentLength>()
-        .map(|ct_len| **ct_len)
-        .unwrap_or(0);
-    if !resp.status().is_success() { bail!(Error::Upgrade, "Download request failed with status: {:?}", resp.status()) }
-    download_to_file_with_progress(&mut resp, &mut tmp_tarball, content_length, show_progress)?;
+    /// Set whether the download/overwrite confirmation prompt should be skipped
+    pub fn no_confirm(&mut self, no_confirm: bool) -> &mut Self {
+        self.no_confirm = no_confirm;
+        self
+    }
 
-    print!("Extracting tarball to temp-dir...");
-    io::stdout().flush()?;
-    let new_exe = extract_tarball(&tmp_tarball_path, &tmp_dir.path())?;
-    println!(" ✓");
+    /// Set whether checksum verification should be skipped, allowing releases
+    /// that don't publish a checksum to be installed. A published `.minisig`
+    /// signature is verified either way.
+    pub fn skip_verify(&mut self, skip_verify: bool) -> &mut Self {
+        self.skip_verify = skip_verify;
+        self
+    }
 
-    print!("Replacing binary file...");
-    io::stdout().flush()?;
-    let tmp_file = tmp_dir.path().join("__clin_backup");
-    replace_exe(&current_exe, &new_exe, &tmp_file)?;
-    println!(" ✓");
+    /// Validate configuration and construct an `Updater`
+    ///
+    /// Errors:
+    ///     * Missing `repo_owner`/`repo_name`
+    ///     * Unable to determine target when none was provided
+    pub fn build(&self) -> Result<Updater> {
+        let repo_owner = self.repo_owner.clone()
+            .ok_or_else(|| format_err!(Error::Upgrade, "`repo_owner` required"))?;
+        let repo_name = self.repo_name.clone()
+            .ok_or_else(|| format_err!(Error::Upgrade, "`repo_name` required"))?;
+        let target = match self.target {
+            Some(ref target) => target.clone(),
+            None => get_target()?,
+        };
+        Ok(Updater {
+            api_url: format!("https://api.github.com/repos/{}/{}/releases", repo_owner, repo_name),
+            bin_name: self.bin_name.clone().unwrap_or_else(|| BIN_NAME.to_owned()),
+            target,
+            current_version: self.current_version.clone().unwrap_or_else(|| CURRENT_VERSION.to_owned()),
+            channel: self.channel,
+            filter: self.filter,
+            show_progress: self.show_progress,
+            no_confirm: self.no_confirm,
+            skip_verify: self.skip_verify,
+        })
+    }
+}
 
-    println!("Complete!");
-    Ok(())
+
+/// Self-updater, constructed via [`Updater::configure`](struct.Updater.html#method.configure)
+pub struct Updater {
+    api_url: String,
+    bin_name: String,
+    target: String,
+    current_version: String,
+    channel: Channel,
+    filter: UpdateFilter,
+    show_progress: bool,
+    no_confirm: bool,
+    skip_verify: bool,
+}
+impl Updater {
+    /// Start building an `Updater`
+    pub fn configure() -> UpdaterBuilder {
+        UpdaterBuilder::new()
+    }
+
+    /// Fetch the full list of releases and select the newest one matching
+    /// `self.channel`, further restricted to critical-only releases when
+    /// `self.filter` is `UpdateFilter::Critical`. Releases are compared by
+    /// parsed semver, not tag-name string ordering, so e.g. `v10.0.0` is
+    /// correctly selected over `v2.0.0`.
+    ///
+    /// Errors:
+    ///     * `reqwest` network errors
+    ///     * Unsuccessful response status
+    ///     * No release matching the configured channel/filter
+    fn resolve_release(&self) -> Result<serde_json::Value> {
+        let mut resp = reqwest::get(self.api_url.as_str())?;
+        if !resp.status().is_success() { bail!(Error::Upgrade, "api request failed with status: {:?}", resp.status()) }
+
+        let releases: Vec<serde_json::Value> = resp.json()?;
+        releases.into_iter()
+            .filter(|release| {
+                let tag = release["tag_name"].as_str().unwrap_or("");
+                let prerelease = release["prerelease"].as_bool().unwrap_or(false);
+                match self.channel {
+                    Channel::Stable => !prerelease,
+                    Channel::Beta => tag.contains("beta"),
+                    Channel::Nightly => tag.contains("nightly"),
+                }
+            })
+            .filter(|release| match self.filter {
+                UpdateFilter::Critical => is_critical_release(release),
+                UpdateFilter::All | UpdateFilter::None => true,
+            })
+            .max_by_key(|release| {
+                let tag = release["tag_name"].as_str().unwrap_or("");
+                parse_semver(tag).ok()
+            })
+            .ok_or_else(|| match self.filter {
+                UpdateFilter::Critical => format_err!(Error::Upgrade, "No critical update available"),
+                UpdateFilter::All | UpdateFilter::None => format_err!(Error::Upgrade, "No release found on the `{:?}` channel", self.channel),
+            })
+    }
+
+    /// Upgrade the current binary to the latest matching release
+    pub fn update(&self) -> Result<()> {
+        let current_exe = env::current_exe()?;
+
+        let latest = self.resolve_release()?;
+
+        let latest_tag = latest["tag_name"].as_str()
+            .ok_or_else(|| format_err!(Error::Upgrade, "No tag_name found for latest release"))?
+            .trim_left_matches("v");
+        let latest_version = parse_semver(latest_tag)?;
+        let current_version = parse_semver(&self.current_version)?;
+        if latest_version.cmp(&current_version) != std::cmp::Ordering::Greater {
+            println!("Already up to date! -- v{}", self.current_version);
+            return Ok(())
+        }
+
+        let mut cache = UpdateCache::load(&self.bin_name);
+        if cache.installed_tag.as_ref().map(String::as_str) == Some(latest_tag) {
+            println!("Already up to date! -- v{} (cached)", latest_tag);
+            return Ok(())
+        }
+
+        println!("New release found! v{} --> v{}", self.current_version, latest_tag);
+
+        let latest_assets = latest["assets"].as_array().ok_or_else(|| format_err!(Error::Upgrade, "No release assets found!"))?;
+
+        let target_assets = latest_assets.iter().map(ReleaseAsset::from_asset).collect::<Result<Vec<ReleaseAsset>>>()?;
+        let target_asset = target_assets.iter()
+            .filter(|ra| ra.name.contains(&self.target) && !ra.name.ends_with(".sha256"))
+            .nth(0)
+            .ok_or_else(|| format_err!(Error::Upgrade, "No release asset found for current target: `{}`", self.target))?;
+        let expected_digest = if self.skip_verify {
+            None
+        } else {
+            let digest = find_expected_digest(&target_assets, &target_asset.name)?;
+            if digest.is_none() {
+                bail!(Error::Upgrade, "No checksum published for `{}`", target_asset.name);
+            }
+            digest
+        };
+        // Signature verification is never skippable via `--skip-verify`: if a release
+        // publishes a `.minisig`, it must be checked regardless of the checksum setting.
+        let signature_name = format!("{}.minisig", target_asset.name);
+        let signature_asset = target_assets.iter().find(|ra| ra.name == signature_name);
+
+        println!("\nclin release status:");
+        println!("  * Current executable: {:?}", current_exe);
+        println!("  * New executable tarball: {:?}", target_asset.name);
+        println!("  * New executable download url: {:?}", target_asset.download_url);
+        println!("\nThe following operations will be executed:");
+        println!("  - Download/extract new release");
+        println!("  - Overwrite current executable with new release");
+        if !self.no_confirm {
+            prompt("Do you want to continue? [Y/n] ")?;
+        }
+
+        let tmp_dir = tempdir::TempDir::new(&format!("{}-download", self.bin_name))?;
+        let tmp_tarball_path = tmp_dir.path().join(&target_asset.name);
+        let mut tmp_tarball = fs::File::create(&tmp_tarball_path)?;
+
+        println!("Downloading...");
+        let client = reqwest::Client::new();
+        let mut req = client.get(&target_asset.download_url);
+        if let Some(ref etag) = cache.asset_etag {
+            req = req.header(reqwest::header::IfNoneMatch::Items(vec![
+                reqwest::header::EntityTag::new(false, etag.clone())
+            ]));
+        }
+        let mut resp = req.send()?;
+        if resp.status() == reqwest::StatusCode::NotModified {
+            println!("Release asset unchanged since last check -- nothing to do");
+            return Ok(())
+        }
+        if !resp.status().is_success() { bail!(Error::Upgrade, "Download request failed with status: {:?}", resp.status()) }
+        let content_length = resp.headers()
+            .get::<reqwest::header::ContentLength>()
+            .map(|ct_len| **ct_len)
+            .unwrap_or(0);
+        let asset_etag = resp.headers()
+            .get::<reqwest::header::ETag>()
+            .map(|et| et.tag().to_owned());
+        let digest = download_to_file_with_progress(&mut resp, &mut tmp_tarball, content_length, self.show_progress)?;
+
+        if let Some(expected) = expected_digest {
+            if digest.to_lowercase() != expected {
+                bail!(Error::Upgrade, "Checksum mismatch for `{}`: expected {}, got {}", target_asset.name, expected, digest);
+            }
+            println!("Checksum verified ✓");
+        }
+
+        if let Some(sig_asset) = signature_asset {
+            let signature_text = reqwest::get(&sig_asset.download_url)?.text()?;
+            let signature = Signature::decode(&signature_text)
+                .map_err(|e| format_err!(Error::Signature, "Unable to parse minisign signature: {}", e))?;
+            let public_key = PublicKey::from_base64(TRUSTED_PUBLIC_KEY)
+                .map_err(|e| format_err!(Error::Signature, "Invalid embedded public key: {}", e))?;
+            let tarball_bytes = fs::read(&tmp_tarball_path)?;
+            public_key.verify(&tarball_bytes, &signature)
+                .map_err(|e| format_err!(Error::Signature, "Release signature verification failed: {}", e))?;
+            println!("Signature verified ✓");
+        }
+
+        print!("Extracting tarball to temp-dir...");
+        io::stdout().flush()?;
+        let new_exe = extract_tarball(&tmp_tarball_path, &tmp_dir.path(), &self.bin_name)?;
+        println!(" ✓");
+
+        print!("Replacing binary file...");
+        io::stdout().flush()?;
+        let tmp_file = tmp_dir.path().join(&format!("__{}_backup", self.bin_name));
+        replace_exe(&current_exe, &new_exe, &tmp_file)?;
+        println!(" ✓");
+
+        cache.installed_tag = Some(latest_tag.to_owned());
+        cache.asset_etag = asset_etag;
+        cache.save(&self.bin_name)?;
+
+        println!("Complete!");
+        Ok(())
+    }
+}
+
+
+/// Upgrade the current binary to the latest release matching `channel`/`filter`,
+/// using the default `jaemk/clin` repo configuration
+pub fn run(show_progress: bool, channel: Channel, filter: UpdateFilter) -> Result<()> {
+    Updater::configure()
+        .repo_owner("jaemk")
+        .repo_name("clin")
+        .bin_name(BIN_NAME)
+        .current_version(CURRENT_VERSION)
+        .channel(channel)
+        .filter(filter)
+        .show_progress(show_progress)
+        .build()?
+        .update()
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    #[test]
+    fn hashes_known_bytes_to_expected_digest() {
+        let mut dest = Vec::new();
+        let digest = download_to_file_with_progress(&b"hello world"[..], &mut dest, 11, false).unwrap();
+        assert_eq!(digest, "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9");
+    }
+
+    #[test]
+    fn can_determine_target_arch() {
+        let target = get_target();
+        assert!(target.is_ok(), "{:?}", target);
+    }
+
+    #[test]
+    fn semver_compares_newer_minor_as_greater() {
+        let newer = parse_semver("v0.10.0").unwrap();
+        let older = parse_semver("v0.9.0").unwrap();
+        assert!(newer > older);
+    }
+
+    #[test]
+    fn semver_compares_newer_major_as_greater_than_lexicographically_larger_tag() {
+        // "v10.0.0" < "v2.0.0" as strings, but v10 is the newer release
+        let newer = parse_semver("v10.0.0").unwrap();
+        let older = parse_semver("v2.0.0").unwrap();
+        assert!(newer > older);
+    }
+
+    #[test]
+    fn detects_critical_marker_in_release_body() {
+        let release = serde_json::json!({"body": "Fixes a security issue.\ncritical: true\n"});
+        assert!(is_critical_release(&release));
+
+        let release = serde_json::json!({"body": "Just some cleanup."});
+        assert!(!is_critical_release(&release));
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "linux", target_env = "musl"))]
+    #[test]
+    fn determines_musl_target() {
+        assert_eq!(get_target().unwrap(), "x86_64-unknown-linux-musl");
+    }
+
+    #[cfg(all(target_arch = "x86_64", target_os = "windows", target_env = "msvc"))]
+    #[test]
+    fn determines_windows_msvc_target() {
+        assert_eq!(get_target().unwrap(), "x86_64-pc-windows-msvc");
+    }
+
+    #[cfg(all(target_arch = "aarch64", target_os = "macos"))]
+    #[test]
+    fn determines_aarch64_darwin_target() {
+        assert_eq!(get_target().unwrap(), "aarch64-apple-darwin");
+    }
+
+    #[cfg(all(target_arch = "arm", target_os = "linux", not(target_env = "musl")))]
+    #[test]
+    fn determines_armv7_gnueabihf_target() {
+        assert_eq!(get_target().unwrap(), "armv7-unknown-linux-gnueabihf");
+    }
 }
 